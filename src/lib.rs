@@ -1,6 +1,11 @@
 use std::{
+    error::Error as StdError,
+    fmt,
     fs::File,
-    io::{BufReader, BufWriter},
+    io::{
+        BufReader, BufWriter, Read, Write,
+    },
+    path::PathBuf,
     process::{Command, ExitStatus},
 };
 
@@ -20,46 +25,407 @@ use inferno::collapse::dtrace::{
 use inferno::{
     collapse::Collapse,
     flamegraph::{
-        from_reader, Options as FlamegraphOptions,
+        color::Palette, from_reader, Direction,
+        Options as FlamegraphOptions,
     },
 };
 
 use signal_hook;
 
+/// The call-graph / stack-unwinding strategy `perf` should
+/// use while sampling. Only meaningful on Linux, where
+/// `perf record --call-graph <mode>` accepts one of these.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CallgraphMode {
+    /// Unwind using DWARF CFI. Slower to collect and
+    /// process, but works for binaries built without frame
+    /// pointers.
+    #[default]
+    Dwarf,
+    /// Unwind using frame pointers. Requires the workload to
+    /// be built with `-C force-frame-pointers=yes`, but is
+    /// much cheaper to sample.
+    Fp,
+    /// Unwind using the CPU's last branch record hardware.
+    /// Only available on CPUs that support LBR.
+    Lbr,
+}
+
+#[cfg(target_os = "linux")]
+impl CallgraphMode {
+    fn as_perf_arg(self) -> &'static str {
+        match self {
+            CallgraphMode::Dwarf => "dwarf",
+            CallgraphMode::Fp => "fp",
+            CallgraphMode::Lbr => "lbr",
+        }
+    }
+}
+
+/// Sampling and profiler configuration, threaded through to
+/// the underlying `perf`/`dtrace` invocation. Each field
+/// mirrors a flag the frontend binary can expose on the
+/// command line, so users can tune profiling without forking
+/// this crate.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Sampling frequency in Hertz, passed to `perf record
+    /// -F` (or used to build the DTrace `profile-<freq>`
+    /// probe). Defaults to `99` on Linux and `997` with
+    /// DTrace, matching each platform's previous hardcoded
+    /// value, both chosen to avoid lockstep with common
+    /// timer frequencies.
+    pub frequency: u32,
+
+    /// Call-graph/unwind mode for `perf record
+    /// --call-graph`. Only applies on Linux.
+    #[cfg(target_os = "linux")]
+    pub cg_mode: CallgraphMode,
+
+    /// Maximum stack depth to record. On Linux, with the
+    /// dwarf call-graph mode, this is appended to
+    /// `--call-graph` as the dump size (`dwarf,<value>`);
+    /// the fp and lbr modes do not use it. On other
+    /// platforms it becomes the DTrace `ustackframes` value.
+    /// Defaults to `100`.
+    pub max_stack_depth: u32,
+
+    /// Escape hatch for passing arbitrary extra arguments
+    /// straight through to the profiler command, inserted
+    /// after the flags derived from the other fields above.
+    pub custom_cmd_args: Vec<String>,
+
+    /// If set, the collapsed stack data produced by the
+    /// profiler run is also written to this path, so the
+    /// same profile can be re-rendered later with
+    /// `generate_flamegraph_from_folded` without
+    /// re-profiling the workload.
+    pub collapsed_file: Option<PathBuf>,
+
+    /// Title printed at the top of the rendered SVG.
+    /// Defaults to inferno's own default ("Flame Graph")
+    /// when unset.
+    pub title: Option<String>,
+
+    /// Subtitle printed underneath the title.
+    pub subtitle: Option<String>,
+
+    /// Color palette used to paint stack frames, e.g. hot,
+    /// mem, io, or one of the differential palettes.
+    pub palette: Option<Palette>,
+
+    /// Frames narrower than this percentage of the total
+    /// width are elided from the output.
+    pub min_width: Option<f64>,
+
+    /// Width of the rendered SVG in pixels. Defaults to
+    /// inferno's own default when unset.
+    pub image_width: Option<usize>,
+
+    /// Render an icicle graph (root at the top, growing
+    /// downward) instead of the usual flame graph (root at
+    /// the bottom, growing upward).
+    pub inverted: bool,
+
+    /// Reverse the ordering of stack frames, merging from
+    /// the leaf end inward instead of the root end outward.
+    pub reverse_stack_order: bool,
+}
+
+/// What `perf`/`dtrace` should sample.
+#[derive(Debug, Clone)]
+pub enum Workload {
+    /// Spawn this command and profile it until it exits.
+    Command(String),
+    /// Attach to an already-running process with this PID
+    /// and sample it until the user sends SIGINT.
+    Pid(u32),
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            #[cfg(target_os = "linux")]
+            frequency: 99,
+            #[cfg(not(target_os = "linux"))]
+            frequency: 997,
+            #[cfg(target_os = "linux")]
+            cg_mode: CallgraphMode::default(),
+            max_stack_depth: 100,
+            custom_cmd_args: Vec::new(),
+            collapsed_file: None,
+            title: None,
+            subtitle: None,
+            palette: None,
+            min_width: None,
+            image_width: None,
+            inverted: false,
+            reverse_stack_order: false,
+        }
+    }
+}
+
+fn flamegraph_options(
+    options: &Options,
+) -> FlamegraphOptions<'_> {
+    let mut flamegraph_options = FlamegraphOptions::default();
+
+    if let Some(title) = &options.title {
+        flamegraph_options.title = title.clone();
+    }
+    flamegraph_options.subtitle = options.subtitle.clone();
+    if let Some(palette) = options.palette {
+        flamegraph_options.colors = palette;
+    }
+    if let Some(min_width) = options.min_width {
+        flamegraph_options.min_width = min_width;
+    }
+    flamegraph_options.image_width = options.image_width;
+    flamegraph_options.direction = if options.inverted {
+        Direction::Inverted
+    } else {
+        Direction::Straight
+    };
+    flamegraph_options.reverse_stack_order =
+        options.reverse_stack_order;
+
+    flamegraph_options
+}
+
+/// Everything that can go wrong while generating a
+/// flamegraph. Returned instead of panicking, so that
+/// library consumers can handle failures (missing
+/// `perf`/`dtrace` binary, an empty sample set, permission
+/// denied, ...) programmatically, and so the binary frontend
+/// can decide on a deterministic exit code from one place.
+#[derive(Debug)]
+pub enum FlamegraphError {
+    RegisterSignalHandler(std::io::Error),
+    SpawnProfiler {
+        profiler: &'static str,
+        source: std::io::Error,
+    },
+    WaitForProfiler {
+        profiler: &'static str,
+        source: std::io::Error,
+    },
+    ProfilerExitedWithError,
+    CollectProfilerOutput(std::io::Error),
+    OpenProfilerOutputFile(std::io::Error),
+    ReadProfilerOutputFile(std::io::Error),
+    RemoveProfilerOutputFile(std::io::Error),
+    Collapse(Box<dyn StdError + Send + Sync>),
+    CreateCollapsedFile(std::io::Error),
+    WriteCollapsedFile(std::io::Error),
+    CreateFlamegraphFile(std::io::Error),
+    Render(Box<dyn StdError + Send + Sync>),
+}
+
+impl fmt::Display for FlamegraphError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            FlamegraphError::RegisterSignalHandler(e) => {
+                write!(
+                    f,
+                    "cannot register signal handler: {}",
+                    e
+                )
+            }
+            FlamegraphError::SpawnProfiler {
+                profiler,
+                source,
+            } => {
+                write!(
+                    f,
+                    "could not spawn {}: {}",
+                    profiler, source
+                )
+            }
+            FlamegraphError::WaitForProfiler {
+                profiler,
+                source,
+            } => write!(
+                f,
+                "unable to wait for {} child command \
+                 to exit: {}",
+                profiler, source
+            ),
+            FlamegraphError::ProfilerExitedWithError => {
+                write!(f, "failed to sample program")
+            }
+            FlamegraphError::CollectProfilerOutput(e) => {
+                write!(
+                    f,
+                    "unable to collect profiler output: {}",
+                    e
+                )
+            }
+            FlamegraphError::OpenProfilerOutputFile(e) => {
+                write!(
+                    f,
+                    "failed to open profiler output file: {}",
+                    e
+                )
+            }
+            FlamegraphError::ReadProfilerOutputFile(e) => {
+                write!(
+                    f,
+                    "failed to read profiler output file: {}",
+                    e
+                )
+            }
+            FlamegraphError::RemoveProfilerOutputFile(e) => {
+                write!(
+                    f,
+                    "unable to remove temporary profiler \
+                     output file: {}",
+                    e
+                )
+            }
+            FlamegraphError::Collapse(e) => write!(
+                f,
+                "unable to collapse generated profile \
+                 data: {}",
+                e
+            ),
+            FlamegraphError::CreateCollapsedFile(e) => {
+                write!(
+                    f,
+                    "unable to create collapsed stacks \
+                     output file: {}",
+                    e
+                )
+            }
+            FlamegraphError::WriteCollapsedFile(e) => {
+                write!(
+                    f,
+                    "unable to write collapsed stacks \
+                     output file: {}",
+                    e
+                )
+            }
+            FlamegraphError::CreateFlamegraphFile(e) => {
+                write!(
+                    f,
+                    "unable to create flamegraph output \
+                     file: {}",
+                    e
+                )
+            }
+            FlamegraphError::Render(e) => write!(
+                f,
+                "unable to generate a flamegraph from the \
+                 collapsed stack data: {}",
+                e
+            ),
+        }
+    }
+}
+
+impl StdError for FlamegraphError {
+    fn source(
+        &self,
+    ) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            FlamegraphError::RegisterSignalHandler(e) => {
+                Some(e)
+            }
+            FlamegraphError::SpawnProfiler {
+                source, ..
+            } => Some(source),
+            FlamegraphError::WaitForProfiler {
+                source, ..
+            } => Some(source),
+            FlamegraphError::ProfilerExitedWithError => {
+                None
+            }
+            FlamegraphError::CollectProfilerOutput(e) => {
+                Some(e)
+            }
+            FlamegraphError::OpenProfilerOutputFile(e) => {
+                Some(e)
+            }
+            FlamegraphError::ReadProfilerOutputFile(e) => {
+                Some(e)
+            }
+            FlamegraphError::RemoveProfilerOutputFile(
+                e,
+            ) => Some(e),
+            FlamegraphError::Collapse(e) => {
+                Some(e.as_ref())
+            }
+            FlamegraphError::CreateCollapsedFile(e) => {
+                Some(e)
+            }
+            FlamegraphError::WriteCollapsedFile(e) => {
+                Some(e)
+            }
+            FlamegraphError::CreateFlamegraphFile(e) => {
+                Some(e)
+            }
+            FlamegraphError::Render(e) => {
+                Some(e.as_ref())
+            }
+        }
+    }
+}
+
 #[cfg(target_os = "linux")]
 mod arch {
     use super::*;
 
-    pub const SPAWN_ERROR: &'static str =
-        "could not spawn perf";
-    pub const WAIT_ERROR: &'static str =
-        "unable to wait for perf \
-         child command to exit";
+    pub const NAME: &'static str = "perf";
 
     pub(crate) fn initial_command(
-        workload: String,
+        workload: Workload,
+        options: &Options,
     ) -> Command {
         let mut command = Command::new("perf");
 
-        for arg in "record -F 99 --call-graph dwarf -g"
-            .split_whitespace()
-        {
+        command.arg("record");
+        command.arg("-F");
+        command.arg(options.frequency.to_string());
+        command.arg("--call-graph");
+        command.arg(match options.cg_mode {
+            // only the dwarf unwinder takes a dump-size
+            // suffix; fp/lbr reject it
+            CallgraphMode::Dwarf => format!(
+                "dwarf,{}",
+                options.max_stack_depth
+            ),
+            other => other.as_perf_arg().to_string(),
+        });
+        command.arg("-g");
+
+        for arg in &options.custom_cmd_args {
             command.arg(arg);
         }
 
-        for item in workload.split_whitespace() {
-            command.arg(item);
+        match workload {
+            Workload::Command(workload) => {
+                for item in workload.split_whitespace() {
+                    command.arg(item);
+                }
+            }
+            Workload::Pid(pid) => {
+                command.arg("-p");
+                command.arg(pid.to_string());
+            }
         }
 
         command
     }
 
-    pub fn output() -> Vec<u8> {
+    pub fn output() -> Result<Vec<u8>, FlamegraphError> {
         Command::new("perf")
             .arg("script")
             .output()
-            .expect("unable to call perf script")
-            .stdout
+            .map(|output| output.stdout)
+            .map_err(FlamegraphError::CollectProfilerOutput)
     }
 }
 
@@ -82,26 +448,36 @@ fn collapse_options() -> CollapseOptions {
 mod arch {
     use super::*;
 
-    pub const SPAWN_ERROR: &'static str =
-        "could not spawn dtrace";
-    pub const WAIT_ERROR: &'static str =
-        "unable to wait for dtrace \
-         child command to exit";
+    pub const NAME: &'static str = "dtrace";
 
     pub(crate) fn initial_command(
-        workload: String,
+        workload: Workload,
+        options: &Options,
     ) -> Command {
         let mut command = Command::new("dtrace");
 
-        let dtrace_script = "profile-997 /pid == $target/ \
-                             { @[ustack(100)] = count(); }";
+        let target_predicate = match &workload {
+            Workload::Command(_) => "$target".to_string(),
+            Workload::Pid(pid) => pid.to_string(),
+        };
+
+        let dtrace_script = format!(
+            "profile-{} /pid == {}/ \
+             {{ @[ustack({})] = count(); }}",
+            options.frequency,
+            target_predicate,
+            options.max_stack_depth
+        );
 
         // DTrace doesn't do a good job demangling
         // Rust names so do it in the collapser instead.
         command.arg("-xmangled");
 
         command.arg("-x");
-        command.arg("ustackframes=100");
+        command.arg(format!(
+            "ustackframes={}",
+            options.max_stack_depth
+        ));
 
         command.arg("-n");
         command.arg(&dtrace_script);
@@ -109,33 +485,41 @@ mod arch {
         command.arg("-o");
         command.arg("cargo-flamegraph.stacks");
 
-        command.arg("-c");
-        command.arg(&workload);
+        for arg in &options.custom_cmd_args {
+            command.arg(arg);
+        }
+
+        match workload {
+            Workload::Command(workload) => {
+                command.arg("-c");
+                command.arg(&workload);
+            }
+            Workload::Pid(pid) => {
+                command.arg("-p");
+                command.arg(pid.to_string());
+            }
+        }
 
         command
     }
 
-    pub fn output() -> Vec<u8> {
+    pub fn output() -> Result<Vec<u8>, FlamegraphError> {
         let mut buf = vec![];
         let mut f = File::open("cargo-flamegraph.stacks")
-            .expect(
-                "failed to open dtrace output \
-                 file cargo-flamegraph.stacks",
-            );
+            .map_err(
+                FlamegraphError::OpenProfilerOutputFile,
+            )?;
 
-        use std::io::Read;
-        f.read_to_end(&mut buf).expect(
-            "failed to read dtrace expected \
-             output file cargo-flamegraph.stacks",
-        );
+        f.read_to_end(&mut buf).map_err(
+            FlamegraphError::ReadProfilerOutputFile,
+        )?;
 
         std::fs::remove_file("cargo-flamegraph.stacks")
-            .expect(
-                "unable to remove cargo-flamegraph.stacks \
-                 temporary file",
-            );
+            .map_err(
+                FlamegraphError::RemoveProfilerOutputFile,
+            )?;
 
-        buf
+        Ok(buf)
     }
 }
 
@@ -158,9 +542,10 @@ fn terminated_by_error(status: ExitStatus) -> bool {
 pub fn generate_flamegraph_by_running_command<
     P: AsRef<std::path::Path>,
 >(
-    workload: String,
+    workload: Workload,
     flamegraph_filename: P,
-) {
+    options: Options,
+) -> Result<(), FlamegraphError> {
     // Handle SIGINT with an empty handler. This has the
     // implicit effect of allowing the signal to reach the
     // process under observation while we continue to
@@ -169,29 +554,44 @@ pub fn generate_flamegraph_by_running_command<
     // process group).
     let handler = unsafe {
         signal_hook::register(signal_hook::SIGINT, || {})
-            .expect("cannot register signal handler")
+            .map_err(FlamegraphError::RegisterSignalHandler)?
     };
 
-    let mut command = arch::initial_command(workload);
+    let mut command =
+        arch::initial_command(workload, &options);
+
+    let spawn_result = command.spawn();
+    let mut recorder = match spawn_result {
+        Ok(recorder) => recorder,
+        Err(source) => {
+            signal_hook::unregister(handler);
+            return Err(FlamegraphError::SpawnProfiler {
+                profiler: arch::NAME,
+                source,
+            });
+        }
+    };
 
-    let mut recorder =
-        command.spawn().expect(arch::SPAWN_ERROR);
+    let wait_result = recorder.wait();
+    signal_hook::unregister(handler);
 
     let exit_status =
-        recorder.wait().expect(arch::WAIT_ERROR);
-
-    signal_hook::unregister(handler);
+        wait_result.map_err(|source| {
+            FlamegraphError::WaitForProfiler {
+                profiler: arch::NAME,
+                source,
+            }
+        })?;
 
     // only stop if perf exited unsuccessfully, but
     // was not killed by a signal (assuming that the
     // latter case usually means the user interrupted
     // it in some way)
     if terminated_by_error(exit_status) {
-        eprintln!("failed to sample program");
-        std::process::exit(1);
+        return Err(FlamegraphError::ProfilerExitedWithError);
     }
 
-    let output = arch::output();
+    let output = arch::output()?;
 
     let perf_reader = BufReader::new(&*output);
 
@@ -201,34 +601,373 @@ pub fn generate_flamegraph_by_running_command<
 
     Folder::from(collapse_options())
         .collapse(perf_reader, collapsed_writer)
-        .expect(
-            "unable to collapse generated profile data",
-        );
+        .map_err(|e| {
+            FlamegraphError::Collapse(Box::new(e))
+        })?;
+
+    if let Some(collapsed_file) = &options.collapsed_file {
+        let mut f = File::create(collapsed_file)
+            .map_err(FlamegraphError::CreateCollapsedFile)?;
+        f.write_all(&collapsed)
+            .map_err(FlamegraphError::WriteCollapsedFile)?;
+    }
 
     let collapsed_reader = BufReader::new(&*collapsed);
 
+    generate_flamegraph_from_folded(
+        collapsed_reader,
+        flamegraph_filename,
+        options,
+    )
+}
+
+/// Generate a flamegraph straight from pre-collapsed folded
+/// stack data, skipping the profiler and collapse stages
+/// entirely. This accepts the same folded format that
+/// `inferno::flamegraph::from_reader` understands (lines of
+/// the form `frame_a;frame_b;frame_c <count>`), so it can
+/// render flamegraphs from anything that emits folded
+/// stacks, such as `tracing-flame`.
+pub fn generate_flamegraph_from_folded<
+    R: Read,
+    P: AsRef<std::path::Path>,
+>(
+    reader: R,
+    flamegraph_filename: P,
+    options: Options,
+) -> Result<(), FlamegraphError> {
     println!(
         "writing flamegraph to {:?}",
         flamegraph_filename.as_ref()
     );
 
     let flamegraph_file = File::create(flamegraph_filename)
-        .expect(
-            "unable to create flamegraph.svg output file",
-        );
+        .map_err(FlamegraphError::CreateFlamegraphFile)?;
 
     let flamegraph_writer = BufWriter::new(flamegraph_file);
 
     let mut flamegraph_options =
-        FlamegraphOptions::default();
+        flamegraph_options(&options);
 
     from_reader(
         &mut flamegraph_options,
-        collapsed_reader,
+        reader,
         flamegraph_writer,
     )
-    .expect(
-        "unable to generate a flamegraph \
-         from the collapsed stack data",
-    );
+    .map_err(|e| FlamegraphError::Render(Box::new(e)))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<&str> {
+        command
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn flamegraph_options_maps_inverted_to_direction() {
+        let options = Options {
+            inverted: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            flamegraph_options(&options).direction,
+            Direction::Inverted
+        );
+
+        let options = Options {
+            inverted: false,
+            ..Options::default()
+        };
+        assert_eq!(
+            flamegraph_options(&options).direction,
+            Direction::Straight
+        );
+    }
+
+    #[test]
+    fn flamegraph_options_leaves_unset_fields_at_inferno_defaults(
+    ) {
+        let options = Options::default();
+        let defaults = FlamegraphOptions::default();
+
+        let mapped = flamegraph_options(&options);
+
+        assert_eq!(mapped.title, defaults.title);
+        assert_eq!(
+            mapped.image_width,
+            defaults.image_width
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn initial_command_defaults_to_dwarf_call_graph() {
+        let options = Options::default();
+        let command =
+            arch::initial_command(
+                Workload::Command("a b".into()),
+                &options,
+            );
+
+        assert_eq!(
+            args(&command),
+            vec![
+                "record", "-F", "99", "--call-graph",
+                "dwarf,100", "-g", "a", "b",
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn initial_command_fp_call_graph_has_no_depth_suffix() {
+        let options = Options {
+            cg_mode: CallgraphMode::Fp,
+            ..Options::default()
+        };
+        let command =
+            arch::initial_command(
+                Workload::Command("a".into()),
+                &options,
+            );
+
+        assert_eq!(
+            args(&command),
+            vec![
+                "record", "-F", "99", "--call-graph", "fp",
+                "-g", "a",
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn initial_command_lbr_call_graph_has_no_depth_suffix() {
+        let options = Options {
+            cg_mode: CallgraphMode::Lbr,
+            ..Options::default()
+        };
+        let command =
+            arch::initial_command(
+                Workload::Command("a".into()),
+                &options,
+            );
+
+        assert_eq!(
+            args(&command),
+            vec![
+                "record", "-F", "99", "--call-graph", "lbr",
+                "-g", "a",
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn initial_command_honors_custom_frequency_and_depth()
+    {
+        let options = Options {
+            frequency: 49,
+            max_stack_depth: 64,
+            ..Options::default()
+        };
+        let command =
+            arch::initial_command(
+                Workload::Command("a".into()),
+                &options,
+            );
+
+        assert_eq!(
+            args(&command),
+            vec![
+                "record", "-F", "49", "--call-graph",
+                "dwarf,64", "-g", "a",
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn initial_command_appends_custom_cmd_args() {
+        let options = Options {
+            custom_cmd_args: vec!["--no-inherit".into()],
+            ..Options::default()
+        };
+        let command =
+            arch::initial_command(
+                Workload::Command("a".into()),
+                &options,
+            );
+
+        assert_eq!(
+            args(&command),
+            vec![
+                "record", "-F", "99", "--call-graph",
+                "dwarf,100", "-g", "--no-inherit", "a",
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn initial_command_attaches_to_pid() {
+        let options = Options::default();
+        let command = arch::initial_command(
+            Workload::Pid(1234),
+            &options,
+        );
+
+        assert_eq!(
+            args(&command),
+            vec![
+                "record", "-F", "99", "--call-graph",
+                "dwarf,100", "-g", "-p", "1234",
+            ]
+        );
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn initial_command_uses_the_dtrace_sampling_default() {
+        let options = Options::default();
+        let command =
+            arch::initial_command(
+                Workload::Command("a".into()),
+                &options,
+            );
+
+        assert_eq!(
+            args(&command),
+            vec![
+                "-xmangled",
+                "-x",
+                "ustackframes=100",
+                "-n",
+                "profile-997 /pid == $target/ \
+                 { @[ustack(100)] = count(); }",
+                "-o",
+                "cargo-flamegraph.stacks",
+                "-c",
+                "a",
+            ]
+        );
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn initial_command_honors_custom_frequency_and_depth()
+    {
+        let options = Options {
+            frequency: 49,
+            max_stack_depth: 64,
+            ..Options::default()
+        };
+        let command =
+            arch::initial_command(
+                Workload::Command("a".into()),
+                &options,
+            );
+
+        assert_eq!(
+            args(&command),
+            vec![
+                "-xmangled",
+                "-x",
+                "ustackframes=64",
+                "-n",
+                "profile-49 /pid == $target/ \
+                 { @[ustack(64)] = count(); }",
+                "-o",
+                "cargo-flamegraph.stacks",
+                "-c",
+                "a",
+            ]
+        );
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn initial_command_appends_custom_cmd_args() {
+        let options = Options {
+            custom_cmd_args: vec!["-Z".into()],
+            ..Options::default()
+        };
+        let command =
+            arch::initial_command(
+                Workload::Command("a".into()),
+                &options,
+            );
+
+        assert_eq!(
+            args(&command),
+            vec![
+                "-xmangled",
+                "-x",
+                "ustackframes=100",
+                "-n",
+                "profile-997 /pid == $target/ \
+                 { @[ustack(100)] = count(); }",
+                "-o",
+                "cargo-flamegraph.stacks",
+                "-Z",
+                "-c",
+                "a",
+            ]
+        );
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn initial_command_attaches_to_pid() {
+        let options = Options::default();
+        let command = arch::initial_command(
+            Workload::Pid(1234),
+            &options,
+        );
+
+        assert_eq!(
+            args(&command),
+            vec![
+                "-xmangled",
+                "-x",
+                "ustackframes=100",
+                "-n",
+                "profile-997 /pid == 1234/ \
+                 { @[ustack(100)] = count(); }",
+                "-o",
+                "cargo-flamegraph.stacks",
+                "-p",
+                "1234",
+            ]
+        );
+    }
+
+    #[test]
+    fn generate_flamegraph_from_folded_writes_svg_file() {
+        let folded = "a;b;c 1\na;b;d 2\n";
+        let path = std::env::temp_dir().join(format!(
+            "flamegraph-test-{}.svg",
+            std::process::id()
+        ));
+
+        generate_flamegraph_from_folded(
+            folded.as_bytes(),
+            &path,
+            Options::default(),
+        )
+        .expect("failed to generate flamegraph");
+
+        let svg = std::fs::read_to_string(&path)
+            .expect("flamegraph file was not written");
+        assert!(svg.contains("<svg"));
+
+        std::fs::remove_file(&path).ok();
+    }
 }